@@ -0,0 +1,307 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    camera::ExtractedCamera,
+    render_resource::{
+        BindGroupLayout, BindGroupLayoutEntries, BlendComponent, BlendFactor, BlendOperation,
+        BlendState, CachedRenderPipelineId, ColorTargetState, ColorWrites, Extent3d,
+        FragmentState, PipelineCache, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+        SamplerDescriptor, ShaderStages, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureSampleType, TextureUsages,
+    },
+    renderer::RenderDevice,
+    texture::{CachedTexture, TextureCache},
+    view::ViewTarget,
+    Render, RenderApp, RenderSet,
+};
+
+use crate::{
+    core_3d::CORE_3D_DEPTH_FORMAT, fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+};
+
+use super::{OitTechnique, OrderIndependentTransparencySettings, OIT_DEPTH_PEEL_SHADER_HANDLE};
+
+pub mod node;
+
+/// Depth peeling renders the transparent phase once per layer, so an
+/// unbounded `passes` would make a single misconfigured camera freeze the
+/// frame. Mirrors how `layer_count` overflow is handled for the layered
+/// linked list technique: rather than failing, we just cap quality.
+pub const MAX_PASSES: u8 = 16;
+
+/// The two ping-ponged depth textures used to track "what's already been
+/// peeled" (read as `oit_depth_peel_previous_depth` by the pass that's about
+/// to extract the next layer, written as that pass's own depth attachment),
+/// the single-layer target each pass's extracted fragments are drawn into,
+/// and the target those single layers are composited into, back to front.
+#[derive(Component, Clone)]
+pub struct OitDepthPeelTextures {
+    pub depth: [CachedTexture; 2],
+    pub current_layer: CachedTexture,
+    pub accumulation: CachedTexture,
+}
+
+pub struct OitDepthPeelPlugin;
+
+impl Plugin for OitDepthPeelPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.add_systems(
+            Render,
+            prepare_oit_depth_peel_textures.in_set(RenderSet::PrepareResources),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<OitDepthPeelDrawBindGroupLayout>()
+            .init_resource::<OitDepthPeelCompositePipeline>()
+            .init_resource::<OitDepthPeelResolvePipeline>();
+    }
+}
+
+fn prepare_oit_depth_peel_textures(
+    mut commands: Commands,
+    mut texture_cache: ResMut<TextureCache>,
+    render_device: Res<RenderDevice>,
+    cameras: Query<(Entity, &ExtractedCamera, &OrderIndependentTransparencySettings)>,
+) {
+    for (entity, camera, settings) in &cameras {
+        if !matches!(settings.technique, OitTechnique::DepthPeeling { .. }) {
+            continue;
+        }
+
+        let Some(size) = camera.physical_target_size else {
+            continue;
+        };
+
+        let extent = Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+
+        let mut make_depth_texture = |label| {
+            texture_cache.get(
+                &render_device,
+                TextureDescriptor {
+                    label: Some(label),
+                    size: extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: CORE_3D_DEPTH_FORMAT,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+            )
+        };
+
+        let depth = [
+            make_depth_texture("oit_depth_peel_depth_texture_a"),
+            make_depth_texture("oit_depth_peel_depth_texture_b"),
+        ];
+
+        let mut make_layer_texture = |label| {
+            texture_cache.get(
+                &render_device,
+                TextureDescriptor {
+                    label: Some(label),
+                    size: extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba16Float,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+            )
+        };
+
+        let current_layer = make_layer_texture("oit_depth_peel_current_layer_texture");
+        let accumulation = make_layer_texture("oit_depth_peel_accumulation_texture");
+
+        commands.entity(entity).insert(OitDepthPeelTextures {
+            depth,
+            current_layer,
+            accumulation,
+        });
+    }
+}
+
+/// Layout for the bind group `OitDepthPeelNode` builds each pass to supply
+/// `oit_depth_peel_previous_depth` (`@group(2) @binding(60)` in
+/// `oit_depth_peel.wgsl`) to the transparent phase's pipelines, so a
+/// fragment already extracted by an earlier pass can discard itself.
+#[derive(Resource)]
+pub struct OitDepthPeelDrawBindGroupLayout(pub BindGroupLayout);
+
+impl FromWorld for OitDepthPeelDrawBindGroupLayout {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        // Must be declared at binding index 60 to match
+        // `oit_depth_peel_previous_depth`'s `@group(2) @binding(60)` in
+        // `oit_depth_peel.wgsl` — `BindGroupLayoutEntries::single` would
+        // place it at index 0 instead, making this layout incompatible with
+        // that shader's group 2.
+        Self(render_device.create_bind_group_layout(
+            "oit_depth_peel_draw_bind_group_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::FRAGMENT,
+                ((60, bevy_render::render_resource::binding_types::texture_depth_2d()),),
+            ),
+        ))
+    }
+}
+
+/// Composites one pass's extracted `current_layer` under whatever's already
+/// in `accumulation`, using the "under" operator (`ONE_MINUS_DST_ALPHA, ONE`
+/// for both color and alpha) so that peeling nearest-to-farthest still ends
+/// up with farther layers correctly blended *behind* nearer ones, regardless
+/// of the blend state the transparent phase's own pipelines use while
+/// drawing into `current_layer`.
+#[derive(Resource)]
+pub struct OitDepthPeelCompositePipeline {
+    pub bind_group_layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for OitDepthPeelCompositePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "oit_depth_peel_composite_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    bevy_render::render_resource::binding_types::texture_2d(
+                        TextureSampleType::Float { filterable: true },
+                    ),
+                    bevy_render::render_resource::binding_types::sampler(
+                        SamplerBindingType::Filtering,
+                    ),
+                ),
+            ),
+        );
+
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline_id = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+            label: Some("oit_depth_peel_composite_pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: OIT_DEPTH_PEEL_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "resolve".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::Rgba16Float,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::OneMinusDstAlpha,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::OneMinusDstAlpha,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            push_constant_ranges: vec![],
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipeline_id,
+        }
+    }
+}
+
+/// Composites the fully peeled `accumulation` texture (already correctly
+/// sorted, premultiplied alpha) back over the opaque scene.
+#[derive(Resource)]
+pub struct OitDepthPeelResolvePipeline {
+    pub bind_group_layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for OitDepthPeelResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "oit_depth_peel_resolve_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    bevy_render::render_resource::binding_types::texture_2d(
+                        TextureSampleType::Float { filterable: true },
+                    ),
+                    bevy_render::render_resource::binding_types::sampler(
+                        SamplerBindingType::Filtering,
+                    ),
+                ),
+            ),
+        );
+
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline_id = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+            label: Some("oit_depth_peel_resolve_pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: OIT_DEPTH_PEEL_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "resolve".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: ViewTarget::TEXTURE_FORMAT_HDR,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::OneMinusSrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::OneMinusSrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            push_constant_ranges: vec![],
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipeline_id,
+        }
+    }
+}
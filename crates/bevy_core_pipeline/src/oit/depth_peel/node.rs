@@ -0,0 +1,221 @@
+use bevy_ecs::{query::QueryItem, world::World};
+use bevy_render::{
+    render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+    render_phase::ViewSortedRenderPhases,
+    render_resource::{
+        BindGroupEntries, LoadOp, Operations, PipelineCache, RenderPassColorAttachment,
+        RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
+    },
+    renderer::RenderContext,
+    view::{ExtractedView, ViewTarget},
+};
+
+use crate::{
+    core_3d::Transparent3d,
+    oit::{OitTechnique, OrderIndependentTransparencySettings},
+};
+
+use super::{
+    OitDepthPeelCompositePipeline, OitDepthPeelDrawBindGroupLayout, OitDepthPeelResolvePipeline,
+    OitDepthPeelTextures, MAX_PASSES,
+};
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct OitDepthPeelPass;
+
+/// Extracts exactly `passes` transparent layers front-to-back by rendering
+/// the transparent phase once per layer into `current_layer`, each time
+/// discarding fragments at or in front of the depth the previous pass wrote, then
+/// composites `current_layer` *under* `accumulation` so farther layers end
+/// up correctly behind nearer ones. Once all layers are extracted,
+/// `accumulation` is composited back over the opaque scene. Runs for every
+/// camera using [`OitTechnique::DepthPeeling`]; other techniques are
+/// skipped.
+#[derive(Default)]
+pub struct OitDepthPeelNode;
+
+impl ViewNode for OitDepthPeelNode {
+    type ViewQuery = (
+        &'static ExtractedView,
+        &'static ViewTarget,
+        &'static OrderIndependentTransparencySettings,
+        Option<&'static OitDepthPeelTextures>,
+    );
+
+    fn run<'w>(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (view, target, settings, textures): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let OitTechnique::DepthPeeling { passes } = settings.technique else {
+            return Ok(());
+        };
+        let Some(textures) = textures else {
+            return Ok(());
+        };
+        let passes = passes.min(MAX_PASSES);
+        if passes == 0 {
+            return Ok(());
+        }
+
+        let transparent_phases = world.resource::<ViewSortedRenderPhases<Transparent3d>>();
+        let Some(transparent_phase) = transparent_phases.get(&graph.view_entity()) else {
+            return Ok(());
+        };
+
+        let draw_bind_group_layout = world.resource::<OitDepthPeelDrawBindGroupLayout>();
+        let composite_pipeline_res = world.resource::<OitDepthPeelCompositePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(composite_pipeline) =
+            pipeline_cache.get_render_pipeline(composite_pipeline_res.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        // Pass 0 has no earlier pass to compare against, so it must read a
+        // "previous depth" of all-zero (never discard, see
+        // `oit_depth_peel_should_discard`). Textures pulled from the cache
+        // aren't guaranteed to already be zeroed, so explicitly clear the
+        // slot pass 0 will read before the peel loop starts.
+        render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("oit_depth_peel_clear_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &textures.depth[1].default_view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(0.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        // Peel front-to-back: each pass reads the depth texture the
+        // *previous* pass wrote (ping-ponged through `textures.depth`) to
+        // discard already-extracted fragments, and draws the next layer it
+        // finds into `current_layer`.
+        for pass in 0..passes {
+            let write_depth = &textures.depth[pass as usize % 2].default_view;
+            let read_depth = &textures.depth[(pass as usize + 1) % 2].default_view;
+
+            // Must target binding index 60 to match
+            // `OitDepthPeelDrawBindGroupLayout`'s entry (and
+            // `oit_depth_peel_previous_depth`'s `@group(2) @binding(60)` in
+            // `oit_depth_peel.wgsl`).
+            let previous_depth_bind_group = render_context.render_device().create_bind_group(
+                "oit_depth_peel_draw_bind_group",
+                &draw_bind_group_layout.0,
+                &BindGroupEntries::with_indices(((60, read_depth),)),
+            );
+
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("oit_depth_peel_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &textures.current_layer.default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Default::default()),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: write_depth,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(0.0),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            if let Some(viewport) = &view.viewport {
+                render_pass.set_camera_viewport(viewport);
+            }
+
+            render_pass.set_bind_group(2, &previous_depth_bind_group, &[]);
+            transparent_phase.render(&mut render_pass, world, graph.view_entity());
+
+            drop(render_pass);
+
+            // Composite this pass's single extracted layer *under* whatever
+            // earlier passes have already accumulated, so farther layers
+            // (extracted by later passes) end up correctly behind nearer
+            // ones no matter what blend state the transparent phase's own
+            // pipelines used while drawing into `current_layer`.
+            let composite_bind_group = render_context.render_device().create_bind_group(
+                "oit_depth_peel_composite_bind_group",
+                &composite_pipeline_res.bind_group_layout,
+                &BindGroupEntries::sequential((
+                    &textures.current_layer.default_view,
+                    &composite_pipeline_res.sampler,
+                )),
+            );
+
+            let mut composite_pass =
+                render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                    label: Some("oit_depth_peel_composite_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &textures.accumulation.default_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: if pass == 0 {
+                                LoadOp::Clear(Default::default())
+                            } else {
+                                LoadOp::Load
+                            },
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+
+            composite_pass.set_render_pipeline(composite_pipeline);
+            composite_pass.set_bind_group(0, &composite_bind_group, &[]);
+            composite_pass.draw(0..3, 0..1);
+        }
+
+        let resolve_pipeline = world.resource::<OitDepthPeelResolvePipeline>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(resolve_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "oit_depth_peel_resolve_bind_group",
+            &resolve_pipeline.bind_group_layout,
+            &BindGroupEntries::sequential((
+                &textures.accumulation.default_view,
+                &resolve_pipeline.sampler,
+            )),
+        );
+
+        let mut resolve_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("oit_depth_peel_resolve_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target.main_texture_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        resolve_pass.set_render_pipeline(pipeline);
+        resolve_pass.set_bind_group(0, &bind_group, &[]);
+        resolve_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
@@ -1,3 +1,8 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
 use bevy_app::prelude::*;
 use bevy_asset::{load_internal_asset, Handle};
 use bevy_ecs::prelude::*;
@@ -5,8 +10,13 @@ use bevy_math::UVec2;
 use bevy_render::{
     camera::ExtractedCamera,
     extract_component::{ExtractComponent, ExtractComponentPlugin},
+    extract_resource::{ExtractResource, ExtractResourcePlugin},
     render_graph::{RenderGraphApp, ViewNodeRunner},
-    render_resource::{BufferUsages, BufferVec, Shader, TextureUsages},
+    render_resource::{
+        Buffer, BufferDescriptor, BufferUsages, BufferVec, CachedRenderPipelineId,
+        CommandEncoderDescriptor, DynamicUniformBuffer, Maintain, MapMode, Shader, ShaderType,
+        TextureUsages,
+    },
     renderer::{RenderDevice, RenderQueue},
     view::Msaa,
     Render, RenderApp, RenderSet,
@@ -16,31 +26,170 @@ use resolve::{
     node::{OitResolveNode, OitResolvePass},
     OitResolvePlugin,
 };
+use wboit::{
+    node::{
+        OitWboitAccumulateNode, OitWboitAccumulatePass, OitWboitResolveNode, OitWboitResolvePass,
+    },
+    OitWboitResolvePlugin,
+};
+use depth_peel::{
+    node::{OitDepthPeelNode, OitDepthPeelPass},
+    OitDepthPeelPlugin,
+};
 
 use crate::core_3d::{
     graph::{Core3d, Node3d},
     Camera3d,
 };
 
+pub mod depth_peel;
 pub mod resolve;
+pub mod wboit;
 
 pub const OIT_DRAW_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(4042527984320512);
+pub const OIT_WBOIT_ACCUMULATE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(4042527984320513);
+pub const OIT_WBOIT_RESOLVE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(4042527984320514);
+pub const OIT_DEPTH_PEEL_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(4042527984320516);
+
+// TODO consider supporting more OIT techniques like Moment Based OIT,
+// stochastic transparency, ray tracing etc.
+/// Which algorithm is used to resolve order independent transparency.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OitTechnique {
+    /// Stores every transparent fragment touching a pixel in a per-pixel linked
+    /// list, then sorts and blends them in a resolve pass. Gives the most
+    /// correct result but its buffers scale with `width * height * layer_count`,
+    /// which can be hundreds of MiB and isn't available on some platforms
+    /// (mobile, WebGL). Supports MSAA: each sample of a pixel gets its own
+    /// layer list, indexed by `pixel * sample_count + sample_index`, which
+    /// multiplies that memory cost by the camera's sample count.
+    #[default]
+    LayeredLinkedList,
+    /// Weighted Blended OIT. Instead of storing every fragment, each
+    /// transparent fragment is accumulated into a fixed-size "accumulation"
+    /// and "revealage" render target using an approximate depth/alpha based
+    /// weight. This uses a small, constant amount of memory regardless of how
+    /// many transparent layers overlap a pixel, at the cost of being only an
+    /// approximation: it can't perfectly reconstruct paint order, so it tends
+    /// to look worse with many overlapping, very different colored layers.
+    WeightedBlended,
+    /// Depth peeling. The transparent phase is rendered `passes` times; each
+    /// pass discards every fragment at or in front of the depth extracted by
+    /// the previous pass, so it extracts exactly one more (farther) layer per
+    /// pass, which is composited under the layers already accumulated from
+    /// earlier passes. This gives exact, correctly sorted compositing (no
+    /// approximation like [`OitTechnique::WeightedBlended`]) without the
+    /// unbounded per-pixel storage of [`OitTechnique::LayeredLinkedList`], at
+    /// the cost of rendering the transparent geometry `passes` times.
+    DepthPeeling {
+        /// How many layers to extract. Pixels with more overlapping
+        /// transparent layers than this will be missing their farthest
+        /// layers, same as `layer_count` overflowing for the layered linked
+        /// list technique. Clamped to [`depth_peel::MAX_PASSES`].
+        passes: u8,
+    },
+}
 
-// TODO consider supporting multiple OIT techniques like WBOIT, Moment Based OIT,
-// depth peeling, stochastic transparency, ray tracing etc.
-// This should probably be done by adding an enum to this component
 #[derive(Component, Clone, Copy, ExtractComponent)]
 pub struct OrderIndependentTransparencySettings {
-    // TODO actually send that value to the shader
-    layer_count: u8,
+    /// Controls which OIT algorithm is used to resolve transparency for this
+    /// camera.
+    pub technique: OitTechnique,
+    /// Only used by [`OitTechnique::LayeredLinkedList`]: the maximum number of
+    /// transparent layers tracked per pixel. Fragments beyond this count are
+    /// dropped. Higher values cost more memory (`width * height * layer_count`
+    /// in the shared [`OitBuffers`]). Clamped to [`MAX_LAYER_COUNT`], since
+    /// the resolve shader's per-pixel sort scratch is a fixed-size array.
+    pub layer_count: u8,
 }
 
+/// The resolve shader sorts each pixel's layers in a fixed-size array (see
+/// `oit_resolve.wgsl`), so `layer_count` can't exceed this without writing
+/// out of bounds.
+pub const MAX_LAYER_COUNT: u8 = 32;
+
 impl Default for OrderIndependentTransparencySettings {
     fn default() -> Self {
-        Self { layer_count: 8 }
+        Self {
+            technique: OitTechnique::default(),
+            layer_count: 8,
+        }
+    }
+}
+
+/// The per-camera data the layered linked list technique's draw and resolve
+/// shaders need: how many layers this camera was given, and where in the
+/// shared [`OitBuffers`] its slice starts. Several OIT cameras in the same
+/// frame are each assigned a non-overlapping slice by [`prepare_oit_buffers`].
+#[derive(ShaderType, Clone, Copy, Default)]
+pub struct OitLayersOffsetUniform {
+    pub layer_count: u32,
+    pub layers_offset: u32,
+    /// This camera's starting element offset into [`OitBuffers::layer_ids`],
+    /// mirroring `layers_offset` for [`OitBuffers::layers`]. Without this,
+    /// two OIT cameras would both index `oit_layer_ids` from zero and stomp
+    /// each other's atomic layer counters.
+    pub layer_ids_offset: u32,
+    pub screen_width: u32,
+    /// The camera's MSAA sample count (1 for a camera with no [`Msaa`]
+    /// component or [`Msaa::Off`]). Each sample gets its own layer list, so
+    /// draw and resolve both index the shared buffers by
+    /// `pixel_index * sample_count + sample_index`.
+    pub sample_count: u32,
+}
+
+/// Points at this camera's entry in [`OitBuffers::offsets`].
+#[derive(Component, Clone, Copy)]
+pub struct OitLayersOffset {
+    pub uniform_offset: u32,
+}
+
+/// The [`resolve::OitResolvePipeline`] specialized for this camera's MSAA
+/// sample count, assigned by `resolve::queue_oit_resolve_pipelines`. Pipeline
+/// specialization needs mutable access to the pipeline cache, which isn't
+/// available from [`resolve::node::OitResolveNode`], so it's done ahead of
+/// time in a regular system and the result is stashed here.
+#[derive(Component, Clone, Copy)]
+pub struct CachedOitResolvePipelineId(pub CachedRenderPipelineId);
+
+/// Caps how much GPU memory [`OitTechnique::LayeredLinkedList`] cameras may
+/// collectively use for their per-pixel layer buffers. When the layer counts
+/// requested by [`OrderIndependentTransparencySettings`] would exceed this,
+/// [`prepare_oit_buffers`] scales them down instead of allocating past the
+/// budget, trading layer overflow artifacts for a known memory ceiling.
+/// Each camera keeps at least one layer, even when that alone exceeds the
+/// budget.
+#[derive(Resource, Clone, Copy, ExtractResource)]
+pub struct OitMemoryBudget {
+    pub max_bytes: u64,
+}
+
+impl Default for OitMemoryBudget {
+    fn default() -> Self {
+        // 256 MiB: generous enough for one full-HD OIT camera at the default
+        // layer_count (about 135 MiB), small enough to not be a surprise.
+        Self {
+            max_bytes: 256 * 1024 * 1024,
+        }
     }
 }
 
+/// Observability for the layered linked list technique: the most layers any
+/// pixel stored this frame (never more than its camera's `layer_count`, even
+/// where more were drawn), and how many pixels ran out of layers
+/// and had fragments silently dropped. Populated from a small atomic counter
+/// buffer that `oit_draw.wgsl` writes into; since reading it back to the CPU
+/// takes a couple of frames to resolve, these numbers lag slightly behind
+/// what's currently on screen.
+#[derive(Resource, Clone, Copy, Default)]
+pub struct OitStats {
+    pub peak_layers: u32,
+    pub overflowed_pixel_count: u32,
+}
+
 pub struct OrderIndependentTransparencyPlugin;
 impl Plugin for OrderIndependentTransparencyPlugin {
     fn build(&self, app: &mut bevy_app::App) {
@@ -50,26 +199,72 @@ impl Plugin for OrderIndependentTransparencyPlugin {
             "oit_draw.wgsl",
             Shader::from_wgsl
         );
+        load_internal_asset!(
+            app,
+            OIT_WBOIT_ACCUMULATE_SHADER_HANDLE,
+            "oit_wboit_accumulate.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            OIT_WBOIT_RESOLVE_SHADER_HANDLE,
+            "wboit/oit_wboit_resolve.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            OIT_DEPTH_PEEL_SHADER_HANDLE,
+            "depth_peel/oit_depth_peel.wgsl",
+            Shader::from_wgsl
+        );
 
-        app.add_plugins((
-            ExtractComponentPlugin::<OrderIndependentTransparencySettings>::default(),
-            OitResolvePlugin,
-        ))
-        .add_systems(Update, check_msaa)
-        .add_systems(Last, configure_depth_texture_usages);
+        app.init_resource::<OitMemoryBudget>()
+            .add_plugins((
+                ExtractComponentPlugin::<OrderIndependentTransparencySettings>::default(),
+                ExtractResourcePlugin::<OitMemoryBudget>::default(),
+                OitResolvePlugin,
+                OitWboitResolvePlugin,
+                OitDepthPeelPlugin,
+            ))
+            .add_systems(Last, configure_depth_texture_usages);
 
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
         };
 
-        render_app.add_systems(
-            Render,
-            prepare_oit_buffers.in_set(RenderSet::PrepareResources),
-        );
+        render_app
+            .init_resource::<OitStats>()
+            .add_systems(
+                Render,
+                (
+                    read_oit_stats.before(prepare_oit_buffers),
+                    prepare_oit_buffers,
+                )
+                    .in_set(RenderSet::PrepareResources),
+            )
+            .add_systems(Render, copy_and_reset_oit_stats.in_set(RenderSet::Cleanup));
 
         render_app
             .add_render_graph_node::<ViewNodeRunner<OitResolveNode>>(Core3d, OitResolvePass)
-            .add_render_graph_edges(Core3d, (Node3d::MainTransparentPass, OitResolvePass));
+            .add_render_graph_edges(Core3d, (Node3d::MainTransparentPass, OitResolvePass))
+            .add_render_graph_node::<ViewNodeRunner<OitWboitAccumulateNode>>(
+                Core3d,
+                OitWboitAccumulatePass,
+            )
+            .add_render_graph_node::<ViewNodeRunner<OitWboitResolveNode>>(
+                Core3d,
+                OitWboitResolvePass,
+            )
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::MainTransparentPass,
+                    OitWboitAccumulatePass,
+                    OitWboitResolvePass,
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<OitDepthPeelNode>>(Core3d, OitDepthPeelPass)
+            .add_render_graph_edges(Core3d, (Node3d::MainTransparentPass, OitDepthPeelPass));
     }
 
     fn finish(&self, app: &mut bevy_app::App) {
@@ -92,23 +287,33 @@ fn configure_depth_texture_usages(mut new_cameras: Query<&mut Camera3d, Added<Ca
     }
 }
 
-fn check_msaa(cameras: Query<&Msaa, With<OrderIndependentTransparencySettings>>) {
-    for msaa in &cameras {
-        if msaa.samples() > 1 {
-            warn_once!(
-                "MSAA should be disabled when using Order Independent Transparency. \
-                It will cause some rendering issues on some platform. Consider using another AA method."
-            );
-        }
-    }
-}
-
 #[derive(Resource)]
 pub struct OitBuffers {
     pub layers: BufferVec<UVec2>,
     pub layer_ids: BufferVec<i32>,
+    /// One [`OitLayersOffsetUniform`] per [`OitTechnique::LayeredLinkedList`]
+    /// camera active this frame, indexed by each camera's
+    /// [`OitLayersOffset::uniform_offset`].
+    pub offsets: DynamicUniformBuffer<OitLayersOffsetUniform>,
+    /// `[peak_layers, overflowed_pixel_count]`, written by `oit_draw.wgsl` and
+    /// reset every frame once its value has been copied into [`OitStats`].
+    pub stats_buffer: Buffer,
+    /// A single staging buffer would have [`copy_and_reset_oit_stats`] copy
+    /// into it again before a previous frame's `map_async` resolved (GPU->CPU
+    /// readback lags a frame or two), which is a use-after/during-map wgpu
+    /// validation error. Cycling through several staging buffers, each with
+    /// its own in-flight flag, gives an outstanding map time to finish before
+    /// that slot is reused.
+    stats_staging_buffers: Vec<Buffer>,
+    stats_staging_in_flight: Vec<Arc<AtomicBool>>,
+    stats_staging_next: usize,
+    stats_readback: Arc<Mutex<Option<[u32; 2]>>>,
 }
 
+/// How many staging buffers [`OitBuffers`] cycles through for stats readback.
+/// Two frames of readback lag plus one frame of slack.
+const STATS_STAGING_BUFFER_COUNT: usize = 3;
+
 impl FromWorld for OitBuffers {
     fn from_world(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
@@ -124,43 +329,277 @@ impl FromWorld for OitBuffers {
         layer_ids.reserve(0, render_device);
         layer_ids.write_buffer(render_device, render_queue);
 
-        Self { layers, layer_ids }
+        let stats_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("oit_stats_buffer"),
+            size: 8,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        render_queue.write_buffer(&stats_buffer, 0, &[0u8; 8]);
+
+        let stats_staging_buffers = (0..STATS_STAGING_BUFFER_COUNT)
+            .map(|_| {
+                render_device.create_buffer(&BufferDescriptor {
+                    label: Some("oit_stats_staging_buffer"),
+                    size: 8,
+                    usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        let stats_staging_in_flight = (0..STATS_STAGING_BUFFER_COUNT)
+            .map(|_| Arc::new(AtomicBool::new(false)))
+            .collect();
+
+        Self {
+            layers,
+            layer_ids,
+            offsets: DynamicUniformBuffer::default(),
+            stats_buffer,
+            stats_staging_buffers,
+            stats_staging_in_flight,
+            stats_staging_next: 0,
+            stats_readback: Arc::new(Mutex::new(None)),
+        }
     }
 }
 
-/// This creates or resizes the oit buffers for each camera
-/// It will always create one big buffer that's as big as the biggest buffer needed
-/// Cameras with smaller viewports or less layers will simply use the big buffer and ignore the rest
+/// Pulls in whatever [`OitStats`] readback finished mapping since last frame.
+/// GPU->CPU readback always lags a frame or two behind what's on screen; this
+/// just surfaces the latest value that's actually ready rather than stalling
+/// the frame to wait for it.
+fn read_oit_stats(device: Res<RenderDevice>, buffers: Res<OitBuffers>, mut stats: ResMut<OitStats>) {
+    device.poll(Maintain::Poll);
+
+    if let Some(values) = buffers.stats_readback.lock().unwrap().take() {
+        stats.peak_layers = values[0];
+        stats.overflowed_pixel_count = values[1];
+    }
+}
+
+/// Copies this frame's stats into the next staging buffer in the ring for
+/// [`read_oit_stats`] to pick up once mapping completes, then zeroes the live
+/// buffer so next frame's `oit_draw.wgsl` invocations start counting from
+/// scratch. If that slot's previous map hasn't resolved yet, the copy is
+/// skipped for this frame rather than racing it.
+fn copy_and_reset_oit_stats(
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    mut buffers: ResMut<OitBuffers>,
+) {
+    let index = buffers.stats_staging_next;
+    buffers.stats_staging_next = (index + 1) % buffers.stats_staging_buffers.len();
+
+    if buffers.stats_staging_in_flight[index].load(Ordering::Acquire) {
+        // `write_buffer` is ordered ahead of any `queue.submit`, so even with
+        // no copy this frame the reset must still happen here rather than
+        // being skipped, or next frame's `oit_draw.wgsl` invocations would
+        // keep adding to stale counts.
+        queue.write_buffer(&buffers.stats_buffer, 0, &[0u8; 8]);
+        return;
+    }
+
+    let staging = buffers.stats_staging_buffers[index].clone();
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(&buffers.stats_buffer, 0, &staging, 0, 8);
+    queue.submit([encoder.finish()]);
+
+    // Only reset after the copy has been submitted: `write_buffer` is
+    // ordered ahead of the queue's pending submissions, so resetting before
+    // `submit` would have the copy observe the zeroed buffer instead of this
+    // frame's actual counts.
+    queue.write_buffer(&buffers.stats_buffer, 0, &[0u8; 8]);
+
+    buffers.stats_staging_in_flight[index].store(true, Ordering::Release);
+    let in_flight = buffers.stats_staging_in_flight[index].clone();
+    let readback = buffers.stats_readback.clone();
+    let staging_for_map = staging.clone();
+    staging
+        .slice(..)
+        .map_async(MapMode::Read, move |result| {
+            if result.is_ok() {
+                let bytes = staging_for_map.slice(..).get_mapped_range();
+                let values = [
+                    u32::from_ne_bytes(bytes[0..4].try_into().unwrap()),
+                    u32::from_ne_bytes(bytes[4..8].try_into().unwrap()),
+                ];
+                drop(bytes);
+                staging_for_map.unmap();
+                *readback.lock().unwrap() = Some(values);
+            }
+            in_flight.store(false, Ordering::Release);
+        });
+}
+
+/// What a single [`OitTechnique::LayeredLinkedList`] camera needs from the
+/// shared [`OitBuffers`], gathered from its ECS components. Plain data so
+/// [`layout_oit_cameras`] can compute the actual layout without touching the
+/// `World` or a `RenderDevice`, which keeps that math unit-testable.
+#[derive(Clone, Copy)]
+struct OitCameraRequest {
+    size: UVec2,
+    layer_count: u8,
+    sample_count: u32,
+}
+
+/// Where one camera's slice starts in each of [`OitBuffers`]' shared buffers,
+/// and the (possibly budget-clamped) layer count it was actually granted.
+struct OitCameraLayout {
+    layer_count: u32,
+    layers_offset: u32,
+    layer_ids_offset: u32,
+    screen_width: u32,
+    sample_count: u32,
+}
+
+/// The result of [`layout_oit_cameras`]: a per-camera layout (same order as
+/// the input requests) plus the total size each shared buffer needs to grow
+/// to in order to fit all of them.
+struct OitBuffersLayout {
+    cameras: Vec<OitCameraLayout>,
+    total_layer_ids_size: usize,
+    total_layers_size: usize,
+}
+
+/// Assigns each [`OitTechnique::LayeredLinkedList`] camera a non-overlapping
+/// slice of the shared buffers, so several OIT cameras can render in the
+/// same frame without stomping each other's layers. A camera with more than
+/// one MSAA sample gets its own layer list per sample (see
+/// [`OitLayersOffsetUniform::sample_count`]), so its slice is that many
+/// times larger than an equivalent non-MSAA camera.
+///
+/// Before assigning slices, the total request is checked against
+/// `max_bytes`: if every camera's requested `layer_count` would together
+/// need more memory than that, all of them are scaled down by the same
+/// factor so the allocation fits, rather than growing past it unbounded.
+/// Every camera keeps at least one layer, so a budget too small for that
+/// minimum is still exceeded. Pure CPU logic, split out of [`prepare_oit_buffers`] so it can be unit
+/// tested without a `RenderDevice`.
+fn layout_oit_cameras(requests: &[OitCameraRequest], max_bytes: u64) -> OitBuffersLayout {
+    let layer_ids_bytes = std::mem::size_of::<i32>() as u64;
+    let layers_bytes = std::mem::size_of::<UVec2>() as u64;
+    let (requested_layer_ids_bytes, requested_layers_bytes) =
+        requests.iter().fold((0u64, 0u64), |(ids, layers), request| {
+            let sample_pixel_count =
+                (request.size.x * request.size.y) as u64 * request.sample_count as u64;
+            (
+                ids + sample_pixel_count * layer_ids_bytes,
+                layers + sample_pixel_count * request.layer_count as u64 * layers_bytes,
+            )
+        });
+    let requested_bytes = requested_layer_ids_bytes + requested_layers_bytes;
+
+    // Scale every camera down by the same factor rather than starving later
+    // cameras to give earlier ones their full quality; cheap compared to the
+    // cost of actually overflowing and losing fragments silently.
+    let scale = if requested_bytes > max_bytes && requested_layers_bytes > 0 {
+        warn_once!(
+            "Order independent transparency would need {} MiB across all cameras, over the \
+            {} MiB OitMemoryBudget; reducing layer_count to fit. Some transparent layers may \
+            be dropped.",
+            requested_bytes / 1024 / 1024,
+            max_bytes / 1024 / 1024,
+        );
+        // The layer_ids part doesn't shrink with layer_count, so only what's
+        // left of the budget after it is shared out among the layers.
+        max_bytes.saturating_sub(requested_layer_ids_bytes) as f64 / requested_layers_bytes as f64
+    } else {
+        1.0
+    };
+
+    let mut total_layer_ids_size = 0usize;
+    let mut total_layers_size = 0usize;
+    let mut cameras = Vec::with_capacity(requests.len());
+    for request in requests {
+        let layer_count =
+            ((request.layer_count as f64 * scale) as u8).clamp(1, MAX_LAYER_COUNT) as usize;
+        let sample_pixel_count =
+            (request.size.x * request.size.y) as usize * request.sample_count as usize;
+
+        cameras.push(OitCameraLayout {
+            layer_count: layer_count as u32,
+            layers_offset: total_layers_size as u32,
+            layer_ids_offset: total_layer_ids_size as u32,
+            screen_width: request.size.x,
+            sample_count: request.sample_count,
+        });
+
+        total_layer_ids_size += sample_pixel_count;
+        total_layers_size += sample_pixel_count * layer_count;
+    }
+
+    OitBuffersLayout {
+        cameras,
+        total_layer_ids_size,
+        total_layers_size,
+    }
+}
+
+/// This creates or resizes the oit buffers for each camera using the layered
+/// linked list technique, and assigns each camera a non-overlapping slice of
+/// the shared buffers (recorded in its [`OitLayersOffset`] and
+/// [`OitLayersOffsetUniform`]) so several OIT cameras can render in the same
+/// frame without stomping each other's layers. The actual layout math is in
+/// [`layout_oit_cameras`].
 #[allow(clippy::type_complexity)]
 pub fn prepare_oit_buffers(
+    mut commands: Commands,
     device: Res<RenderDevice>,
     queue: Res<RenderQueue>,
-    cameras: Query<
-        (&ExtractedCamera, &OrderIndependentTransparencySettings),
-        (
-            Changed<ExtractedCamera>,
-            Changed<OrderIndependentTransparencySettings>,
-        ),
-    >,
+    budget: Res<OitMemoryBudget>,
+    cameras: Query<(
+        Entity,
+        &ExtractedCamera,
+        &OrderIndependentTransparencySettings,
+        Option<&Msaa>,
+    )>,
     mut buffers: ResMut<OitBuffers>,
 ) {
-    let mut max_layer_ids_size = usize::MIN;
-    let mut max_layers_size = usize::MIN;
-    for (camera, settings) in &cameras {
-        let Some(size) = camera.physical_target_size else {
-            continue;
-        };
+    let buffers = &mut *buffers;
+    buffers.offsets.clear();
+
+    let oit_cameras: Vec<_> = cameras
+        .iter()
+        .filter(|(_, _, settings, _)| settings.technique == OitTechnique::LayeredLinkedList)
+        .filter_map(|(entity, camera, settings, msaa)| {
+            Some((
+                entity,
+                OitCameraRequest {
+                    size: camera.physical_target_size?,
+                    layer_count: settings.layer_count,
+                    sample_count: msaa.map_or(1, Msaa::samples),
+                },
+            ))
+        })
+        .collect();
+
+    let requests: Vec<_> = oit_cameras.iter().map(|(_, request)| *request).collect();
+    let layout = layout_oit_cameras(&requests, budget.max_bytes);
 
-        let layer_count = settings.layer_count as usize;
-        let size = (size.x * size.y) as usize;
-        max_layer_ids_size = max_layer_ids_size.max(size);
-        max_layers_size = max_layers_size.max(size * layer_count);
+    for ((entity, _), camera_layout) in oit_cameras.iter().zip(&layout.cameras) {
+        let uniform_offset = buffers.offsets.push(&OitLayersOffsetUniform {
+            layer_count: camera_layout.layer_count,
+            layers_offset: camera_layout.layers_offset,
+            layer_ids_offset: camera_layout.layer_ids_offset,
+            screen_width: camera_layout.screen_width,
+            sample_count: camera_layout.sample_count,
+        });
+
+        commands
+            .entity(*entity)
+            .insert(OitLayersOffset { uniform_offset });
     }
 
-    if buffers.layers.capacity() < max_layers_size {
+    let total_layer_ids_size = layout.total_layer_ids_size;
+    let total_layers_size = layout.total_layers_size;
+
+    buffers.offsets.write_buffer(&device, &queue);
+
+    if buffers.layers.capacity() < total_layers_size {
         let start = Instant::now();
-        buffers.layers.reserve(max_layers_size, &device);
-        let remaining = max_layers_size - buffers.layers.capacity();
+        buffers.layers.reserve(total_layers_size, &device);
+        let remaining = total_layers_size - buffers.layers.capacity();
         for _ in 0..remaining {
             buffers.layers.push(UVec2::ZERO);
         }
@@ -172,10 +611,10 @@ pub fn prepare_oit_buffers(
         );
     }
 
-    if buffers.layer_ids.capacity() < max_layer_ids_size {
+    if buffers.layer_ids.capacity() < total_layer_ids_size {
         let start = Instant::now();
-        buffers.layer_ids.reserve(max_layer_ids_size, &device);
-        let remaining = max_layer_ids_size - buffers.layer_ids.capacity();
+        buffers.layer_ids.reserve(total_layer_ids_size, &device);
+        let remaining = total_layer_ids_size - buffers.layer_ids.capacity();
         for _ in 0..remaining {
             buffers.layer_ids.push(0);
         }
@@ -187,3 +626,103 @@ pub fn prepare_oit_buffers(
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(width: u32, height: u32, layer_count: u8, sample_count: u32) -> OitCameraRequest {
+        OitCameraRequest {
+            size: UVec2::new(width, height),
+            layer_count,
+            sample_count,
+        }
+    }
+
+    #[test]
+    fn single_camera_under_budget_keeps_its_layer_count() {
+        let layout = layout_oit_cameras(
+            &[request(100, 100, 8, 1)],
+            OitMemoryBudget::default().max_bytes,
+        );
+
+        assert_eq!(layout.cameras.len(), 1);
+        assert_eq!(layout.cameras[0].layer_count, 8);
+        assert_eq!(layout.cameras[0].layers_offset, 0);
+        assert_eq!(layout.cameras[0].layer_ids_offset, 0);
+        assert_eq!(layout.total_layer_ids_size, 100 * 100);
+        assert_eq!(layout.total_layers_size, 100 * 100 * 8);
+    }
+
+    #[test]
+    fn several_cameras_get_non_overlapping_offsets() {
+        let layout = layout_oit_cameras(
+            &[request(10, 10, 4, 1), request(20, 10, 2, 1)],
+            OitMemoryBudget::default().max_bytes,
+        );
+
+        assert_eq!(layout.cameras[0].layer_ids_offset, 0);
+        assert_eq!(layout.cameras[0].layers_offset, 0);
+
+        // The second camera's slice starts right after the first camera's.
+        assert_eq!(layout.cameras[1].layer_ids_offset, 10 * 10);
+        assert_eq!(layout.cameras[1].layers_offset, 10 * 10 * 4);
+
+        assert_eq!(layout.total_layer_ids_size, 10 * 10 + 20 * 10);
+        assert_eq!(layout.total_layers_size, 10 * 10 * 4 + 20 * 10 * 2);
+    }
+
+    #[test]
+    fn msaa_camera_is_sized_per_sample() {
+        let layout = layout_oit_cameras(
+            &[request(10, 10, 4, 4)],
+            OitMemoryBudget::default().max_bytes,
+        );
+
+        assert_eq!(layout.total_layer_ids_size, 10 * 10 * 4);
+        assert_eq!(layout.total_layers_size, 10 * 10 * 4 * 4);
+    }
+
+    #[test]
+    fn over_budget_cameras_are_scaled_down_but_never_to_zero() {
+        // A single camera requesting far more than the budget allows.
+        let layout = layout_oit_cameras(&[request(1000, 1000, 255, 1)], 1024);
+
+        assert_eq!(layout.cameras.len(), 1);
+        assert!(layout.cameras[0].layer_count >= 1);
+        assert!(layout.cameras[0].layer_count < 255);
+    }
+
+    #[test]
+    fn over_budget_cameras_are_scaled_by_the_same_factor() {
+        // Two equally-sized, equally-greedy cameras sharing a tight budget
+        // should come out with the same layer_count, not one starving the
+        // other.
+        let layout = layout_oit_cameras(
+            &[request(1000, 1000, 255, 1), request(1000, 1000, 255, 1)],
+            1024,
+        );
+
+        assert_eq!(layout.cameras[0].layer_count, layout.cameras[1].layer_count);
+    }
+
+    #[test]
+    fn layer_count_is_clamped_to_the_resolve_shader_sort_scratch_size() {
+        let layout = layout_oit_cameras(
+            &[request(10, 10, 255, 1)],
+            // Budget generous enough that scaling wouldn't otherwise kick in.
+            OitMemoryBudget::default().max_bytes,
+        );
+
+        assert_eq!(layout.cameras[0].layer_count, MAX_LAYER_COUNT as u32);
+    }
+
+    #[test]
+    fn no_cameras_requests_nothing() {
+        let layout = layout_oit_cameras(&[], OitMemoryBudget::default().max_bytes);
+
+        assert!(layout.cameras.is_empty());
+        assert_eq!(layout.total_layer_ids_size, 0);
+        assert_eq!(layout.total_layers_size, 0);
+    }
+}
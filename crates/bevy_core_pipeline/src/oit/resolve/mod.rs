@@ -0,0 +1,148 @@
+use bevy_app::Plugin;
+use bevy_asset::{load_internal_asset, Handle};
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    render_resource::{
+        binding_types::{storage_buffer_read_only, uniform_buffer},
+        BindGroupLayout, BindGroupLayoutEntries, BlendState, ColorTargetState, ColorWrites,
+        FragmentState,
+        MultisampleState, PipelineCache, RenderPipelineDescriptor, Shader, ShaderStages,
+        SpecializedRenderPipeline, SpecializedRenderPipelines,
+    },
+    renderer::RenderDevice,
+    view::{Msaa, ViewTarget},
+    Render, RenderApp, RenderSet,
+};
+
+use crate::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+
+use super::{CachedOitResolvePipelineId, OitLayersOffsetUniform, OitTechnique};
+
+pub mod node;
+
+const OIT_RESOLVE_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(4042527984320515);
+
+pub struct OitResolvePlugin;
+
+impl Plugin for OitResolvePlugin {
+    fn build(&self, app: &mut bevy_app::App) {
+        load_internal_asset!(
+            app,
+            OIT_RESOLVE_SHADER_HANDLE,
+            "oit_resolve.wgsl",
+            Shader::from_wgsl
+        );
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<SpecializedRenderPipelines<OitResolvePipeline>>()
+            .add_systems(
+                Render,
+                queue_oit_resolve_pipelines.in_set(RenderSet::Queue),
+            );
+    }
+
+    fn finish(&self, app: &mut bevy_app::App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.init_resource::<OitResolvePipeline>();
+    }
+}
+
+/// Composites the layered linked list's sorted, blended output back over the
+/// opaque scene. Specialized per [`Msaa`] sample count, since a camera with
+/// MSAA enabled needs the resolve shader to run once per sample (see
+/// `oit_resolve.wgsl`'s `MULTISAMPLED` shader def) instead of once per pixel.
+#[derive(Resource)]
+pub struct OitResolvePipeline {
+    pub bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for OitResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "oit_resolve_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    storage_buffer_read_only::<bevy_math::UVec2>(false),
+                    storage_buffer_read_only::<i32>(false),
+                    uniform_buffer::<OitLayersOffsetUniform>(true),
+                ),
+            ),
+        );
+
+        Self { bind_group_layout }
+    }
+}
+
+impl SpecializedRenderPipeline for OitResolvePipeline {
+    type Key = u32;
+
+    fn specialize(&self, sample_count: Self::Key) -> RenderPipelineDescriptor {
+        let shader_defs = if sample_count > 1 {
+            vec!["MULTISAMPLED".into()]
+        } else {
+            vec![]
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("oit_resolve_pipeline".into()),
+            layout: vec![self.bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: OIT_RESOLVE_SHADER_HANDLE,
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: ViewTarget::TEXTURE_FORMAT_HDR,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+/// Specializes [`OitResolvePipeline`] for each [`OitTechnique::LayeredLinkedList`]
+/// camera's MSAA sample count and stashes the result in
+/// [`CachedOitResolvePipelineId`] for [`node::OitResolveNode`] to pick up;
+/// pipeline specialization needs mutable access to the pipeline cache, which
+/// a render graph node doesn't have.
+fn queue_oit_resolve_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    resolve_pipeline: Res<OitResolvePipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<OitResolvePipeline>>,
+    cameras: Query<(
+        Entity,
+        &super::OrderIndependentTransparencySettings,
+        Option<&Msaa>,
+    )>,
+) {
+    for (entity, settings, msaa) in &cameras {
+        if settings.technique != OitTechnique::LayeredLinkedList {
+            continue;
+        }
+
+        let sample_count = msaa.map_or(1, Msaa::samples);
+        let pipeline_id = pipelines.specialize(&pipeline_cache, &resolve_pipeline, sample_count);
+        commands
+            .entity(entity)
+            .insert(CachedOitResolvePipelineId(pipeline_id));
+    }
+}
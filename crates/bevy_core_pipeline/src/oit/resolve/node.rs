@@ -0,0 +1,95 @@
+use bevy_ecs::{query::QueryItem, world::World};
+use bevy_render::{
+    render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+    render_resource::{
+        BindGroupEntries, LoadOp, Operations, PipelineCache, RenderPassColorAttachment,
+        RenderPassDescriptor, StoreOp,
+    },
+    renderer::RenderContext,
+    view::ViewTarget,
+};
+
+use crate::oit::{
+    CachedOitResolvePipelineId, OitBuffers, OitLayersOffset, OitTechnique,
+    OrderIndependentTransparencySettings,
+};
+
+use super::OitResolvePipeline;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct OitResolvePass;
+
+/// Sorts and blends the per-pixel layer lists written by `oit_draw.wgsl`
+/// back over the opaque scene. Runs for every camera using
+/// [`OitTechnique::LayeredLinkedList`]; other techniques are skipped.
+#[derive(Default)]
+pub struct OitResolveNode;
+
+impl ViewNode for OitResolveNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static OrderIndependentTransparencySettings,
+        Option<&'static OitLayersOffset>,
+        Option<&'static CachedOitResolvePipelineId>,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (target, settings, offset, pipeline_id): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        if settings.technique != OitTechnique::LayeredLinkedList {
+            return Ok(());
+        }
+        let Some(offset) = offset else {
+            return Ok(());
+        };
+        let Some(pipeline_id) = pipeline_id else {
+            return Ok(());
+        };
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let resolve_pipeline = world.resource::<OitResolvePipeline>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id.0) else {
+            return Ok(());
+        };
+
+        let buffers = world.resource::<OitBuffers>();
+        let Some(uniforms_binding) = buffers.offsets.binding() else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "oit_resolve_bind_group",
+            &resolve_pipeline.bind_group_layout,
+            &BindGroupEntries::sequential((
+                buffers.layers.buffer().unwrap().as_entire_binding(),
+                buffers.layer_ids.buffer().unwrap().as_entire_binding(),
+                uniforms_binding,
+            )),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("oit_resolve_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target.main_texture_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[offset.uniform_offset]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    camera::ExtractedCamera,
+    render_resource::{
+        BindGroupLayout, BindGroupLayoutEntries, BlendComponent, BlendFactor, BlendOperation,
+        BlendState, CachedRenderPipelineId, ColorTargetState, ColorWrites, Extent3d,
+        FragmentState, PipelineCache, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+        SamplerDescriptor, ShaderStages, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureSampleType, TextureUsages,
+    },
+    renderer::RenderDevice,
+    texture::{CachedTexture, TextureCache},
+    view::ViewTarget,
+    Render, RenderApp, RenderSet,
+};
+
+use crate::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+
+use super::{OitTechnique, OrderIndependentTransparencySettings};
+
+pub mod node;
+
+/// The per-view accumulation and revealage render targets used by the
+/// [`OitTechnique::WeightedBlended`] technique. Sized to the camera's
+/// physical target size and recreated whenever that changes, same as any
+/// other view-sized render target in bevy_core_pipeline.
+#[derive(Component, Clone)]
+pub struct OitWboitTextures {
+    pub accumulation_texture: CachedTexture,
+    pub revealage_texture: CachedTexture,
+}
+
+pub struct OitWboitResolvePlugin;
+
+impl Plugin for OitWboitResolvePlugin {
+    fn build(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.add_systems(
+            Render,
+            prepare_oit_wboit_textures.in_set(RenderSet::PrepareResources),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.init_resource::<OitWboitResolvePipeline>();
+    }
+}
+
+fn prepare_oit_wboit_textures(
+    mut commands: Commands,
+    mut texture_cache: ResMut<TextureCache>,
+    render_device: Res<RenderDevice>,
+    cameras: Query<(Entity, &ExtractedCamera, &OrderIndependentTransparencySettings)>,
+) {
+    for (entity, camera, settings) in &cameras {
+        if settings.technique != OitTechnique::WeightedBlended {
+            continue;
+        }
+
+        let Some(size) = camera.physical_target_size else {
+            continue;
+        };
+
+        let extent = Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+
+        let accumulation_texture = texture_cache.get(
+            &render_device,
+            TextureDescriptor {
+                label: Some("oit_wboit_accumulation_texture"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba16Float,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+
+        let revealage_texture = texture_cache.get(
+            &render_device,
+            TextureDescriptor {
+                label: Some("oit_wboit_revealage_texture"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+
+        commands.entity(entity).insert(OitWboitTextures {
+            accumulation_texture,
+            revealage_texture,
+        });
+    }
+}
+
+/// Composites the accumulation/revealage targets written by
+/// [`super::OIT_WBOIT_ACCUMULATE_SHADER_HANDLE`] back over the opaque scene:
+/// `accum.rgb / max(accum.a, 1e-5)` blended in using `revealage` as the
+/// coverage factor.
+#[derive(Resource)]
+pub struct OitWboitResolvePipeline {
+    pub bind_group_layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for OitWboitResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "oit_wboit_resolve_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    bevy_render::render_resource::binding_types::texture_2d(
+                        TextureSampleType::Float { filterable: true },
+                    ),
+                    bevy_render::render_resource::binding_types::texture_2d(
+                        TextureSampleType::Float { filterable: true },
+                    ),
+                    bevy_render::render_resource::binding_types::sampler(
+                        SamplerBindingType::Filtering,
+                    ),
+                ),
+            ),
+        );
+
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline_id = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+            label: Some("oit_wboit_resolve_pipeline".into()),
+            layout: vec![bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: super::OIT_WBOIT_RESOLVE_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: ViewTarget::TEXTURE_FORMAT_HDR,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::SrcAlpha,
+                            dst_factor: BlendFactor::OneMinusSrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::Zero,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            push_constant_ranges: vec![],
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use bevy_ecs::{query::QueryItem, world::World};
+use bevy_render::{
+    render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+    render_phase::ViewSortedRenderPhases,
+    render_resource::{
+        BindGroupEntries, Color, LoadOp, Operations, PipelineCache, RenderPassColorAttachment,
+        RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
+    },
+    renderer::RenderContext,
+    view::{ExtractedView, ViewDepthTexture, ViewTarget},
+};
+
+use crate::{
+    core_3d::Transparent3d,
+    oit::{OitTechnique, OrderIndependentTransparencySettings},
+};
+
+use super::{OitWboitResolvePipeline, OitWboitTextures};
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct OitWboitAccumulatePass;
+
+/// Renders the transparent phase into the weighted blended accumulation and
+/// revealage targets. Each phase item's pipeline is expected to call
+/// `oit_wboit_accumulate()` (see `oit_wboit_accumulate.wgsl`) and write its
+/// two outputs through the blend states documented there, which is what
+/// turns "draw every transparent fragment once" into the running
+/// accumulation sum and revealage product [`OitWboitResolveNode`] later
+/// composites.
+/// Runs for every camera using [`OitTechnique::WeightedBlended`]; other
+/// techniques are skipped.
+#[derive(Default)]
+pub struct OitWboitAccumulateNode;
+
+impl ViewNode for OitWboitAccumulateNode {
+    type ViewQuery = (
+        &'static ExtractedView,
+        &'static ViewDepthTexture,
+        &'static OrderIndependentTransparencySettings,
+        Option<&'static OitWboitTextures>,
+    );
+
+    fn run<'w>(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (view, depth, settings, textures): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        if settings.technique != OitTechnique::WeightedBlended {
+            return Ok(());
+        }
+        let Some(textures) = textures else {
+            return Ok(());
+        };
+
+        let transparent_phases = world.resource::<ViewSortedRenderPhases<Transparent3d>>();
+        let Some(transparent_phase) = transparent_phases.get(&graph.view_entity()) else {
+            return Ok(());
+        };
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("oit_wboit_accumulate_pass"),
+            color_attachments: &[
+                Some(RenderPassColorAttachment {
+                    view: &textures.accumulation_texture.default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                }),
+                Some(RenderPassColorAttachment {
+                    view: &textures.revealage_texture.default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        // Revealage is a running product of `(1.0 - alpha)`,
+                        // so it must start at 1.0 ("fully revealed") before
+                        // any fragment has been blended in.
+                        load: LoadOp::Clear(Color::WHITE),
+                        store: StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: depth.view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        if let Some(viewport) = &view.viewport {
+            render_pass.set_camera_viewport(viewport);
+        }
+
+        transparent_phase.render(&mut render_pass, world, graph.view_entity());
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct OitWboitResolvePass;
+
+/// Composites the weighted blended OIT accumulation/revealage targets back
+/// over the opaque scene. Runs for every camera using
+/// [`OitTechnique::WeightedBlended`]; cameras using the layered linked list
+/// technique (or that haven't been prepared yet) are skipped.
+#[derive(Default)]
+pub struct OitWboitResolveNode;
+
+impl ViewNode for OitWboitResolveNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static OrderIndependentTransparencySettings,
+        Option<&'static OitWboitTextures>,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (target, settings, textures): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        if settings.technique != OitTechnique::WeightedBlended {
+            return Ok(());
+        }
+        let Some(textures) = textures else {
+            return Ok(());
+        };
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let resolve_pipeline = world.resource::<OitWboitResolvePipeline>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(resolve_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "oit_wboit_resolve_bind_group",
+            &resolve_pipeline.bind_group_layout,
+            &BindGroupEntries::sequential((
+                &textures.accumulation_texture.default_view,
+                &textures.revealage_texture.default_view,
+                &resolve_pipeline.sampler,
+            )),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("oit_wboit_resolve_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target.main_texture_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}